@@ -1,16 +1,18 @@
 use std::{path::PathBuf, io::Write};
+use bytes::Bytes;
 use miette::{Result, IntoDiagnostic, Context};
 use schemars::JsonSchema;
 use serde::Deserialize;
+use tokio_stream::wrappers::ReceiverStream;
 
-#[derive(Debug, Deserialize, JsonSchema)]
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
 pub struct ImageGenConfig {
     pub headline_bounds: Rect,
     pub max_font_size: i32,
     pub template: PathBuf,
 }
 
-#[derive(Debug, Deserialize, JsonSchema)]
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
 pub struct Rect {
     pub x: i32,
     pub y: i32,
@@ -69,3 +71,45 @@ pub fn generate_image<W: Write>(config: &ImageGenConfig, text: &str, out: &mut W
     ctx.target().write_to_png(out).into_diagnostic()?;
     Ok(())
 }
+
+/// a [`Write`] that forwards each chunk cairo gives it straight out over a
+/// channel, instead of accumulating them anywhere.
+struct ChannelWriter {
+    tx: tokio::sync::mpsc::Sender<Result<Bytes>>,
+}
+
+impl Write for ChannelWriter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.tx
+            .blocking_send(Ok(Bytes::copy_from_slice(buf)))
+            .map_err(|_| {
+                std::io::Error::new(std::io::ErrorKind::BrokenPipe, "image stream receiver dropped")
+            })?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+/// same rendering as [`generate_image`], but piped out as a stream of chunks
+/// instead of buffered into a `Vec<u8>` - cairo's rendering is blocking, so
+/// this runs it on its own thread rather than tying up the async runtime.
+///
+/// a render failure is sent as an `Err` item rather than just ending the
+/// stream early, so consumers can't mistake a broken render for a short-but-
+/// complete image.
+pub fn generate_image_stream(
+    config: ImageGenConfig,
+    text: String,
+) -> impl futures::Stream<Item = Result<Bytes>> + Send + 'static {
+    let (tx, rx) = tokio::sync::mpsc::channel::<Result<Bytes>>(8);
+    std::thread::spawn(move || {
+        let mut writer = ChannelWriter { tx: tx.clone() };
+        if let Err(err) = generate_image(&config, &text, &mut writer) {
+            let _ = tx.blocking_send(Err(err));
+        }
+    });
+    ReceiverStream::new(rx)
+}
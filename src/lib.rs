@@ -0,0 +1,7 @@
+pub mod cli;
+pub mod image;
+pub mod news;
+pub mod publisher;
+pub mod queue;
+pub mod store;
+pub mod tumblr;
@@ -1,14 +1,17 @@
 use clap::Parser;
 use destielbot_rs::{
     cli::{Cli, ConfigFileArgs},
-    image::{generate_image, ImageGenConfig},
-    news::{request_news_source, NewsSource, NewsStory},
+    image::{generate_image, generate_image_stream, ImageGenConfig},
+    news::{NewsSource, NewsSourceFetch, NewsStory},
+    publisher::{MediaSource, MicropubApiConfig, Publisher, PublisherConfig},
+    queue::{BackoffConfig, Job, PostQueue, PostQueueConfig},
+    store::{SeenStore, SeenStoreConfig},
+    tumblr::{TokenStore, TumblrApiConfig},
 };
-use futures::StreamExt;
-use miette::{Context, IntoDiagnostic, Result};
+use miette::{miette, Context, IntoDiagnostic, Result};
 use schemars::JsonSchema;
 use serde::Deserialize;
-use std::collections::HashSet;
+use std::{collections::HashMap, path::PathBuf, sync::Arc};
 use tokio::{fs, io::AsyncWriteExt};
 use tracing_subscriber::{prelude::*, Layer};
 
@@ -21,6 +24,15 @@ struct Config {
     /// api endpoints to pull articles from
     news_sources: Vec<NewsSource>,
     postprocessors: Vec<Postprocessor>,
+    /// where to persist which stories we've already seen/posted, so a restart
+    /// doesn't re-post everything
+    #[serde(default)]
+    seen_store: SeenStoreConfig,
+    /// where to persist in-flight/failed posts, so a restart doesn't drop them
+    #[serde(default)]
+    post_queue: PostQueueConfig,
+    /// where detected stories get posted to - a story fans out to all of them
+    publishers: Vec<PublisherConfig>,
 }
 
 #[derive(Debug, Deserialize, JsonSchema)]
@@ -46,12 +58,20 @@ impl Postprocessor {
 #[derive(Debug, Deserialize, JsonSchema)]
 struct ApiConfig {
     tumblr_api: TumblrApiConfig,
+    /// access tokens for configured micropub publishers, keyed by the
+    /// publisher's `id` in config.json
+    #[serde(default)]
+    micropub: HashMap<String, MicropubApiConfig>,
 }
 
-#[derive(Debug, Deserialize, JsonSchema)]
-pub struct TumblrApiConfig {
-    pub client_id: String,
-    pub client_secret: String,
+async fn run_post_job(publishers: &HashMap<String, Box<dyn Publisher>>, job: &Job) -> Result<()> {
+    let publisher = publishers
+        .get(&job.publisher_id)
+        .ok_or_else(|| miette!("no configured publisher with id {:?}", job.publisher_id))?;
+    publisher
+        .publish(&job.story, MediaSource::Path(job.image_path.clone()))
+        .await
+        .wrap_err_with(|| format!("failed to publish via {:?}", job.publisher_id))
 }
 
 fn load_config(config_info: &ConfigFileArgs) -> Result<(Config, ApiConfig)> {
@@ -165,104 +185,123 @@ async fn main() -> Result<()> {
         destielbot_rs::cli::Commands::Run { config_info } => {
             let (config, apiconfig) = load_config(&config_info)?;
             let client = reqwest::Client::builder().build().into_diagnostic()?;
-            let tumblrclient =
-                tumblr_api::client::Client::new(tumblr_api::client::Credentials::new_oauth2(
-                    apiconfig.tumblr_api.client_id.clone(),
-                    apiconfig.tumblr_api.client_secret.clone(),
-                ));
-            let mut seen_news_urls = HashSet::<String>::new(); // TODO should be saving/loading this so it works across runs?
-            loop {
-                tracing::debug!("polling news sources");
-                // TODO wait the buffer should probably be before the request not after right? oops
-                let cur_stories: Vec<_> = tokio_stream::iter(&config.news_sources)
-                    .map(|source| {
-                        // client is already using an arc internally, so cloning it here doesn't actually clone the underlying stuff
-                        request_news_source(client.clone(), source)
+            let token_store = Arc::new(TokenStore::new(
+                &apiconfig.tumblr_api,
+                PathBuf::from(".oauth2-token.json"),
+            )?);
+            let seen_store: Arc<dyn SeenStore> = Arc::from(config.seen_store.build().await?);
+            let post_queue: Arc<dyn PostQueue> = Arc::from(config.post_queue.build().await?);
+            let publishers: Arc<HashMap<String, Box<dyn Publisher>>> = Arc::new(
+                config
+                    .publishers
+                    .iter()
+                    .map(|p| {
+                        Ok((
+                            p.id().to_string(),
+                            p.build(client.clone(), Arc::clone(&token_store), &apiconfig.micropub)?,
+                        ))
                     })
-                    .buffer_unordered(2)
-                    .filter_map(|x| async move {
-                        match x {
-                            Ok(Some(story)) => Some(story),
-                            Ok(None) => None, // TODO - debug log here that it succeeded but got nothing?
+                    .collect::<Result<HashMap<_, _>>>()?,
+            );
+
+            // drains the post queue concurrently with the polling loop below, so a
+            // slow/failing post doesn't hold up picking up new stories
+            {
+                let queue = Arc::clone(&post_queue);
+                let seen_store = Arc::clone(&seen_store);
+                let publishers = Arc::clone(&publishers);
+                tokio::spawn(async move {
+                    let result = destielbot_rs::queue::run_worker(
+                        queue.as_ref(),
+                        seen_store.as_ref(),
+                        BackoffConfig::default(),
+                        |job: Job| {
+                            let publishers = Arc::clone(&publishers);
+                            async move { run_post_job(&publishers, &job).await }
+                        },
+                    )
+                    .await;
+                    if let Err(err) = result {
+                        tracing::error!("post queue worker exited with an error: {:?}", err);
+                    }
+                });
+            }
+
+            // each source gets its own polling loop on its own interval (rather
+            // than one global sleep between polling *all* sources), so a slow
+            // feed or one source's custom interval doesn't throttle the rest
+            let (story_tx, mut story_rx) = tokio::sync::mpsc::channel::<NewsStory>(32);
+            for source in config.news_sources.clone() {
+                let client = client.clone();
+                let story_tx = story_tx.clone();
+                tokio::spawn(async move {
+                    let mut interval = tokio::time::interval(source.poll_interval());
+                    loop {
+                        interval.tick().await;
+                        tracing::debug!("polling news source {:?}", &source);
+                        match source.poll(&client).await {
+                            Ok(stories) => {
+                                for story in stories {
+                                    if story_tx.send(story).await.is_err() {
+                                        return;
+                                    }
+                                }
+                            }
                             Err(e) => {
                                 // "{:?}" gives the format we want (miette's fancy stuff)
                                 tracing::error!("encountered error while requesting news: {:?}", e);
-                                None
-                            }
-                        }
-                    })
-                    // TODO can we avoid the collect into vec here? we just re-iter it right after
-                    //      the await anyways
-                    .collect::<Vec<_>>()
-                    .await
-                    .into_iter()
-                    .filter_map(|story| {
-                        if seen_news_urls.contains(&story.story_url) {
-                            None
-                        } else {
-                            seen_news_urls.insert(story.story_url.clone());
-                            Some(story)
-                        }
-                    })
-                    // postprocess headlines
-                    .map(|mut story| {
-                        for pp in &config.postprocessors {
-                            pp.postprocess(&mut story);
-                        }
-                        story
-                    })
-                    .collect();
-                if !cur_stories.is_empty() {
-                    tracing::info!("got stories: {:?}", &cur_stories);
-                    for story in cur_stories {
-                        let mut image_data = Vec::<u8>::new();
-                        generate_image(&config.image_gen_cfg, &story.headline, &mut image_data)?;
-                        let create_post_result = tumblrclient
-                            .create_post(
-                                // TODO make target blog settable in config file
-                                "destiel-news-bot",
-                                vec![
-                                    // tumblr_api::npf::ContentBlockText::builder(format!(
-                                    //     "got news story: {:?}",
-                                    //     &story
-                                    // ))
-                                    // .build(),
-                                    tumblr_api::npf::ContentBlockImage::builder(vec![
-                                        tumblr_api::npf::MediaObject::builder(
-                                            tumblr_api::npf::MediaObjectContent::Identifier(
-                                                "image-attachment".into(),
-                                            ),
-                                        )
-                                        .build(),
-                                    ])
-                                    // TODO make alt text template settable in config file
-                                    .alt_text(format!("the destiel confession meme edited to read \"I love you\" / \"{}\"", story.headline))
-                                    .build(),
-                                ],
-                            )
-                            .source_url(story.story_url)
-                            .add_attachment(
-                                reqwest::Body::from(image_data),
-                                "image/png",
-                                "image-attachment",
-                            )
-                            .send()
-                            .await;
-                        match create_post_result {
-                            Err(err) => {
-                                tracing::error!(
-                                    "encountered error trying to post to tumblr: {:?}",
-                                    err
-                                );
-                            }
-                            _ => {
-                                tracing::info!("posted to tumblr successfully");
                             }
                         }
                     }
-                }
+                });
+            }
+            // only the clones handed to the spawned tasks above should keep
+            // the channel open
+            drop(story_tx);
 
-                tokio::time::sleep(std::time::Duration::from_secs(30)).await
+            while let Some(mut story) = story_rx.recv().await {
+                // dedup on the story's stable id, not its url - urls can change
+                // without the story itself changing
+                if seen_store.contains(&story.id).await? {
+                    continue;
+                }
+                for pp in &config.postprocessors {
+                    pp.postprocess(&mut story);
+                }
+                tracing::info!("got story: {:?}", &story);
+                // render once per story and hand every publisher the same
+                // path rather than re-rendering per destination - streamed
+                // straight to a scratch file (not buffered into memory) so
+                // peak memory stays flat no matter how many stories are in
+                // flight at once
+                let image_stream =
+                    generate_image_stream(config.image_gen_cfg.clone(), story.headline.clone());
+                let image_path = std::env::temp_dir().join(format!("destielbot-{}.png", uuid::Uuid::new_v4()));
+                MediaSource::Stream(Box::pin(image_stream))
+                    .write_to_file(&image_path)
+                    .await
+                    .wrap_err("failed to render image")?;
+                // one job per publisher, so a failure posting to one
+                // destination doesn't affect the others' retries
+                for publisher_id in publishers.keys() {
+                    // actually posting happens on the queue worker task, so a
+                    // failed/rate-limited post gets retried instead of lost -
+                    // each publisher's enqueue copies the scratch file into
+                    // its own job storage
+                    post_queue
+                        .enqueue(
+                            story.clone(),
+                            MediaSource::Path(image_path.clone()),
+                            publisher_id.clone(),
+                        )
+                        .await?;
+                }
+                let _ = tokio::fs::remove_file(&image_path).await;
+                // only mark the story seen once every publisher's job is
+                // durably enqueued - if we crash or error out before this, the
+                // next run re-polls and re-enqueues it instead of silently
+                // dropping it
+                seen_store.insert(&story.id).await?;
             }
         }
         destielbot_rs::cli::Commands::ImageTest { config_info } => {
@@ -1,106 +1,261 @@
-use miette::{IntoDiagnostic, Result};
-use reqwest::Url;
-use schemars::JsonSchema;
-use serde::Deserialize;
-use custom_debug::Debug;
-
-#[derive(Debug, Deserialize, JsonSchema)]
-pub enum NewsSource {
-    BBC {
-        #[debug(format = "{}")]
-        url: Url,
-    },
-}
-
-#[derive(Debug, Deserialize, JsonSchema)]
-pub struct NewsSources {
-    pub sources: Vec<NewsSource>,
-}
-
-#[derive(Debug)]
-pub struct NewsStory {
-    pub id: String,
-    pub headline: String,
-    pub story_url: String,
-}
-
-// from https://stackoverflow.com/a/69458453/8762161
-pub fn object_empty_as_none<'de, D, T>(deserializer: D) -> Result<Option<T>, D::Error>
-where
-    D: serde::Deserializer<'de>,
-    for<'a> T: Deserialize<'a>,
-{
-    #[derive(Deserialize, Debug)]
-    #[serde(deny_unknown_fields)]
-    struct Empty {}
-
-    #[derive(Deserialize, Debug)]
-    #[serde(untagged)]
-    enum Aux<T> {
-        T(T),
-        Empty(Empty),
-        Null,
-    }
-
-    match serde::Deserialize::deserialize(deserializer)? {
-        Aux::T(t) => Ok(Some(t)),
-        Aux::Empty(_) | Aux::Null => Ok(None),
-    }
-}
-
-#[derive(Debug, PartialEq, Eq, Deserialize)]
-#[serde(rename_all = "camelCase")]
-struct BBCApiResponseAsset {
-    asset_id: String,
-    asset_uri: String,
-    headline: String,
-}
-
-#[derive(Debug, PartialEq, Eq, Deserialize)]
-struct BBCApiResponse {
-    #[serde(deserialize_with = "object_empty_as_none")]
-    asset: Option<BBCApiResponseAsset>,
-}
-
-#[cfg(test)]
-mod tests {
-    use serde_json::json;
-
-    use crate::news::{BBCApiResponse, BBCApiResponseAsset};
-
-    #[test]
-    fn decode_bbc_response_empty() {
-        assert_eq!(
-            Some(BBCApiResponse { asset: None }),
-            serde_json::from_value::<Option<BBCApiResponse>>(json!({"isError":false,"pollPeriod":30000,"asset":{}})).unwrap()
-        );
-    }
-
-    #[test]
-    fn decode_bbc_response_nonempty() {
-        assert_eq!(
-            Some(BBCApiResponse { asset: Some(BBCApiResponseAsset { asset_id: "1337".to_string(), asset_uri: "/news/uk-1337".to_string(), headline: "Hello World".to_string() }) }),
-            serde_json::from_value::<Option<BBCApiResponse>>(json!({"isError":false,"pollPeriod":30000,"asset":{"assetId":"1337","assetUri":"/news/uk-1337","headline":"Hello World"}})).unwrap()
-        );
-    }
-}
-
-pub async fn request_news_source(client: reqwest::Client, source: NewsSource) -> Result<Option<NewsStory>> {
-    match source {
-        NewsSource::BBC { url } => {
-            let response: BBCApiResponse = client.get(url)
-                .send()
-                .await.into_diagnostic()?
-                .json()
-                .await.into_diagnostic()?;
-            match response.asset {
-                Some(asset) => Ok(Some(NewsStory{
-                    id: format!("BBC_{}", asset.asset_id),
-                    headline: asset.headline,
-                    story_url: format!("https://bbc.co.uk{}", asset.asset_uri),  // TODO - use Url instead?
-                })),
-                _ => Ok(None),
-            }
-        },
-    }
-}
+use std::time::Duration;
+
+use async_trait::async_trait;
+use miette::{Context, IntoDiagnostic, Result};
+use reqwest::Url;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use custom_debug::Debug;
+
+/// how often a source should be polled when it doesn't specify its own
+/// per-source interval
+const DEFAULT_POLL_INTERVAL: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
+pub enum NewsSource {
+    BBC {
+        #[debug(format = "{}")]
+        url: Url,
+    },
+    /// any standard RSS 2.0 or Atom feed
+    Feed {
+        #[debug(format = "{}")]
+        url: Url,
+        /// overrides [`DEFAULT_POLL_INTERVAL`] for this source
+        poll_interval_secs: Option<u64>,
+    },
+    /// a [JSON Feed](https://www.jsonfeed.org/)
+    JsonFeed {
+        #[debug(format = "{}")]
+        url: Url,
+        /// overrides [`DEFAULT_POLL_INTERVAL`] for this source
+        poll_interval_secs: Option<u64>,
+    },
+}
+
+/// fetches new stories from a [`NewsSource`] - pulled out as a trait (rather
+/// than a free function matching on the enum) so adding a new source kind
+/// only means adding a match arm here, not touching every caller.
+#[async_trait]
+pub trait NewsSourceFetch {
+    async fn poll(&self, client: &reqwest::Client) -> Result<Vec<NewsStory>>;
+
+    /// how often this source should be (re)polled
+    fn poll_interval(&self) -> Duration;
+}
+
+#[async_trait]
+impl NewsSourceFetch for NewsSource {
+    async fn poll(&self, client: &reqwest::Client) -> Result<Vec<NewsStory>> {
+        match self {
+            NewsSource::BBC { url } => {
+                let response: BBCApiResponse = client
+                    .get(url.clone())
+                    .send()
+                    .await
+                    .into_diagnostic()?
+                    .json()
+                    .await
+                    .into_diagnostic()?;
+                Ok(match response.asset {
+                    Some(asset) => vec![NewsStory {
+                        id: format!("BBC_{}", asset.asset_id),
+                        headline: asset.headline,
+                        story_url: Url::parse("https://bbc.co.uk")
+                            .into_diagnostic()?
+                            .join(&asset.asset_uri)
+                            .into_diagnostic()?
+                            .to_string(),
+                    }],
+                    None => Vec::new(),
+                })
+            }
+            NewsSource::Feed { url, .. } | NewsSource::JsonFeed { url, .. } => {
+                poll_feed(client, url).await
+            }
+        }
+    }
+
+    fn poll_interval(&self) -> Duration {
+        match self {
+            NewsSource::BBC { .. } => DEFAULT_POLL_INTERVAL,
+            NewsSource::Feed {
+                poll_interval_secs, ..
+            }
+            | NewsSource::JsonFeed {
+                poll_interval_secs, ..
+            } => poll_interval_secs
+                .map(Duration::from_secs)
+                .unwrap_or(DEFAULT_POLL_INTERVAL),
+        }
+    }
+}
+
+/// shared by [`NewsSource::Feed`] and [`NewsSource::JsonFeed`] - `feed-rs`
+/// sniffs the format (RSS 2.0, Atom, or JSON Feed) itself, so there's no need
+/// for separate parsing paths.
+async fn poll_feed(client: &reqwest::Client, url: &Url) -> Result<Vec<NewsStory>> {
+    let body = client
+        .get(url.clone())
+        .send()
+        .await
+        .into_diagnostic()?
+        .bytes()
+        .await
+        .into_diagnostic()?;
+    let feed = feed_rs::parser::parse(&body[..])
+        .into_diagnostic()
+        .wrap_err("failed to parse feed")?;
+    Ok(feed_to_stories(url, feed))
+}
+
+/// pulled out of [`poll_feed`] so the guid/link fallback logic can be unit
+/// tested without needing a live feed to poll.
+fn feed_to_stories(url: &Url, feed: feed_rs::model::Feed) -> Vec<NewsStory> {
+    feed.entries
+        .into_iter()
+        .filter_map(|entry| {
+            let headline = entry.title?.content;
+            // atom entries commonly list a rel="self" or enclosure link
+            // before the article's own rel="alternate" link (the default
+            // when rel is omitted) - prefer that over just taking whichever
+            // link comes first
+            let link = entry
+                .links
+                .iter()
+                .find(|link| matches!(link.rel.as_deref(), Some("alternate") | None))
+                .or_else(|| entry.links.first())?
+                .href
+                .clone();
+            let story_url = url
+                .join(&link)
+                .map(|resolved| resolved.to_string())
+                .unwrap_or_else(|_| link.clone());
+            // prefer the feed's own stable id (guid/atom id) for the dedup
+            // key, explicitly falling back to the (resolved) link when the
+            // entry doesn't have one, so dedup stays stable across polls
+            // even for feeds that omit guid/id
+            let id = if entry.id.is_empty() {
+                story_url.clone()
+            } else {
+                entry.id
+            };
+            Some(NewsStory {
+                id,
+                headline,
+                story_url,
+            })
+        })
+        .collect()
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct NewsSources {
+    pub sources: Vec<NewsSource>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NewsStory {
+    pub id: String,
+    pub headline: String,
+    pub story_url: String,
+}
+
+// from https://stackoverflow.com/a/69458453/8762161
+pub fn object_empty_as_none<'de, D, T>(deserializer: D) -> Result<Option<T>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+    for<'a> T: Deserialize<'a>,
+{
+    #[derive(Deserialize, Debug)]
+    #[serde(deny_unknown_fields)]
+    struct Empty {}
+
+    #[derive(Deserialize, Debug)]
+    #[serde(untagged)]
+    enum Aux<T> {
+        T(T),
+        Empty(Empty),
+        Null,
+    }
+
+    match serde::Deserialize::deserialize(deserializer)? {
+        Aux::T(t) => Ok(Some(t)),
+        Aux::Empty(_) | Aux::Null => Ok(None),
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct BBCApiResponseAsset {
+    asset_id: String,
+    asset_uri: String,
+    headline: String,
+}
+
+#[derive(Debug, PartialEq, Eq, Deserialize)]
+struct BBCApiResponse {
+    #[serde(deserialize_with = "object_empty_as_none")]
+    asset: Option<BBCApiResponseAsset>,
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use crate::news::{feed_to_stories, BBCApiResponse, BBCApiResponseAsset};
+
+    #[test]
+    fn decode_bbc_response_empty() {
+        assert_eq!(
+            Some(BBCApiResponse { asset: None }),
+            serde_json::from_value::<Option<BBCApiResponse>>(json!({"isError":false,"pollPeriod":30000,"asset":{}})).unwrap()
+        );
+    }
+
+    #[test]
+    fn decode_bbc_response_nonempty() {
+        assert_eq!(
+            Some(BBCApiResponse { asset: Some(BBCApiResponseAsset { asset_id: "1337".to_string(), asset_uri: "/news/uk-1337".to_string(), headline: "Hello World".to_string() }) }),
+            serde_json::from_value::<Option<BBCApiResponse>>(json!({"isError":false,"pollPeriod":30000,"asset":{"assetId":"1337","assetUri":"/news/uk-1337","headline":"Hello World"}})).unwrap()
+        );
+    }
+
+    #[test]
+    fn feed_entry_id_falls_back_to_link_when_entry_has_no_guid() {
+        let atom = br#"<?xml version="1.0" encoding="utf-8"?>
+<feed xmlns="http://www.w3.org/2005/Atom">
+  <title>Example</title>
+  <entry>
+    <title>Headline</title>
+    <link rel="alternate" href="/a/story"/>
+  </entry>
+</feed>"#;
+        let feed = feed_rs::parser::parse(&atom[..]).unwrap();
+        let url = reqwest::Url::parse("https://example.com/feed.xml").unwrap();
+        let stories = feed_to_stories(&url, feed);
+        assert_eq!(stories.len(), 1);
+        assert_eq!(stories[0].id, "https://example.com/a/story");
+        assert_eq!(stories[0].story_url, "https://example.com/a/story");
+    }
+
+    #[test]
+    fn feed_prefers_alternate_link_over_enclosure() {
+        let atom = br#"<?xml version="1.0" encoding="utf-8"?>
+<feed xmlns="http://www.w3.org/2005/Atom">
+  <title>Example</title>
+  <entry>
+    <id>story-1</id>
+    <title>Headline</title>
+    <link rel="enclosure" href="https://cdn.example.com/audio.mp3"/>
+    <link rel="alternate" href="https://example.com/a/story-1"/>
+  </entry>
+</feed>"#;
+        let feed = feed_rs::parser::parse(&atom[..]).unwrap();
+        let url = reqwest::Url::parse("https://example.com/feed.xml").unwrap();
+        let stories = feed_to_stories(&url, feed);
+        assert_eq!(stories.len(), 1);
+        assert_eq!(stories[0].id, "story-1");
+        assert_eq!(stories[0].story_url, "https://example.com/a/story-1");
+    }
+}
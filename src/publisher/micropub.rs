@@ -0,0 +1,107 @@
+use async_trait::async_trait;
+use miette::{Context, IntoDiagnostic, Result};
+use reqwest::multipart;
+use schemars::JsonSchema;
+use serde::Deserialize;
+
+use crate::news::NewsStory;
+
+use super::{alt_text_for, MediaSource, Publisher};
+
+/// secrets for a configured [`PublisherConfig::Micropub`][super::PublisherConfig::Micropub]
+/// - kept out of `config.json` (and its schema) the same way [`crate::tumblr::TumblrApiConfig`]
+/// keeps tumblr's client id/secret out of it.
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct MicropubApiConfig {
+    pub access_token: String,
+}
+
+/// posts an `h-entry` to a micropub endpoint, uploading the meme to the
+/// server's media endpoint first and referencing the returned url as the
+/// entry's `photo`.
+pub struct MicropubPublisher {
+    id: String,
+    endpoint: reqwest::Url,
+    media_endpoint: reqwest::Url,
+    access_token: String,
+    client: reqwest::Client,
+}
+
+impl MicropubPublisher {
+    pub fn new(
+        id: String,
+        endpoint: reqwest::Url,
+        media_endpoint: reqwest::Url,
+        access_token: String,
+        client: reqwest::Client,
+    ) -> Self {
+        Self {
+            id,
+            endpoint,
+            media_endpoint,
+            access_token,
+            client,
+        }
+    }
+
+    async fn upload_media(&self, image: MediaSource) -> Result<String> {
+        let part = multipart::Part::stream(image.into_body().await?)
+            .file_name("meme.png")
+            .mime_str("image/png")
+            .into_diagnostic()?;
+        let response = self
+            .client
+            .post(self.media_endpoint.clone())
+            .bearer_auth(&self.access_token)
+            .multipart(multipart::Form::new().part("file", part))
+            .send()
+            .await
+            .into_diagnostic()
+            .wrap_err("failed to upload media to micropub media endpoint")?
+            .error_for_status()
+            .into_diagnostic()
+            .wrap_err("micropub media endpoint rejected the upload")?;
+        response
+            .headers()
+            .get(reqwest::header::LOCATION)
+            .and_then(|v| v.to_str().ok())
+            .map(str::to_string)
+            .ok_or_else(|| miette::miette!("micropub media endpoint did not return a Location header"))
+    }
+}
+
+#[async_trait]
+impl Publisher for MicropubPublisher {
+    fn id(&self) -> &str {
+        &self.id
+    }
+
+    async fn publish(&self, story: &NewsStory, image: MediaSource) -> Result<()> {
+        let photo_url = self.upload_media(image).await?;
+        let alt_text = alt_text_for(&story.headline);
+
+        self.client
+            .post(self.endpoint.clone())
+            .bearer_auth(&self.access_token)
+            .form(&[
+                ("h", "entry"),
+                ("name", &story.headline),
+                ("content", &story.headline),
+                // bracket-array form on both so a compliant server associates
+                // the alt text with this photo, rather than seeing two
+                // unrelated top-level properties
+                ("photo[]", &photo_url),
+                ("photo[][alt]", &alt_text),
+                ("syndication", &story.story_url),
+                ("link", &story.story_url),
+            ])
+            .send()
+            .await
+            .into_diagnostic()
+            .wrap_err("failed to post h-entry to micropub endpoint")?
+            .error_for_status()
+            .into_diagnostic()
+            .wrap_err("micropub endpoint rejected the post")?;
+        Ok(())
+    }
+}
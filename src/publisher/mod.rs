@@ -0,0 +1,164 @@
+//! Where a rendered meme actually gets posted. [`Publisher`] abstracts over the
+//! destination (tumblr, a micropub endpoint, ...) so a single detected story can
+//! fan out to several of them, with each destination's failures/retries
+//! independent of the others.
+
+mod micropub;
+mod tumblr;
+
+use std::{collections::HashMap, path::PathBuf, pin::Pin, sync::Arc};
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use futures::{Stream, StreamExt};
+use miette::{miette, IntoDiagnostic, Result};
+use schemars::JsonSchema;
+use serde::Deserialize;
+
+pub use micropub::{MicropubApiConfig, MicropubPublisher};
+pub use tumblr::TumblrPublisher;
+
+use crate::{news::NewsStory, tumblr::TokenStore};
+
+/// where the bytes for a rendered meme currently live. lets a [`Publisher`]
+/// (or the queue, when persisting a job) consume whichever shape is
+/// cheapest for the caller without forcing a full in-memory copy.
+pub enum MediaSource {
+    InMemory(Bytes),
+    /// a chunk failing (e.g. [`crate::image::generate_image_stream`] hitting
+    /// a render error partway through) fails the whole [`MediaSource`] rather
+    /// than silently truncating the image.
+    Stream(Pin<Box<dyn Stream<Item = Result<Bytes>> + Send>>),
+    Path(PathBuf),
+}
+
+impl MediaSource {
+    /// reads this fully into memory - for backends that need the whole image
+    /// up front (e.g. to know its length before uploading).
+    pub async fn into_bytes(self) -> Result<Bytes> {
+        match self {
+            Self::InMemory(bytes) => Ok(bytes),
+            Self::Stream(mut stream) => {
+                let mut buf = Vec::new();
+                while let Some(chunk) = stream.next().await {
+                    buf.extend_from_slice(&chunk?);
+                }
+                Ok(Bytes::from(buf))
+            }
+            Self::Path(path) => Ok(Bytes::from(tokio::fs::read(path).await.into_diagnostic()?)),
+        }
+    }
+
+    /// converts into a [`reqwest::Body`], streaming straight through rather
+    /// than buffering where possible.
+    pub async fn into_body(self) -> Result<reqwest::Body> {
+        match self {
+            Self::InMemory(bytes) => Ok(reqwest::Body::from(bytes)),
+            Self::Stream(stream) => Ok(reqwest::Body::wrap_stream(stream)),
+            Self::Path(path) => {
+                let file = tokio::fs::File::open(&path).await.into_diagnostic()?;
+                Ok(reqwest::Body::wrap_stream(
+                    tokio_util::io::ReaderStream::new(file),
+                ))
+            }
+        }
+    }
+
+    /// persists this to `path`, streaming straight to disk rather than
+    /// buffering where possible - used by [`crate::queue::PostQueue`]
+    /// backends to store a job's image.
+    pub async fn write_to_file(self, path: &std::path::Path) -> Result<()> {
+        use tokio::io::AsyncWriteExt;
+        match self {
+            Self::InMemory(bytes) => {
+                tokio::fs::write(path, &bytes).await.into_diagnostic()?;
+            }
+            Self::Stream(mut stream) => {
+                let mut file = tokio::fs::File::create(path).await.into_diagnostic()?;
+                while let Some(chunk) = stream.next().await {
+                    file.write_all(&chunk?).await.into_diagnostic()?;
+                }
+            }
+            Self::Path(src) => {
+                tokio::fs::copy(&src, path).await.into_diagnostic()?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+pub trait Publisher: Send + Sync {
+    /// stable id used to route retry-queue jobs back to the publisher that
+    /// should handle them - see [`crate::queue::Job::publisher_id`]
+    fn id(&self) -> &str;
+    async fn publish(&self, story: &NewsStory, image: MediaSource) -> Result<()>;
+}
+
+pub(crate) fn alt_text_for(headline: &str) -> String {
+    format!(
+        "the destiel confession meme edited to read \"I love you\" / \"{}\"",
+        headline
+    )
+}
+
+/// one entry per destination a story should fan out to.
+#[derive(Debug, Deserialize, JsonSchema)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum PublisherConfig {
+    Tumblr {
+        /// unique across all configured publishers - used to route queued/retried jobs
+        id: String,
+        /// target blog's identifier, e.g. `destiel-news-bot`
+        blog: String,
+    },
+    Micropub {
+        id: String,
+        endpoint: reqwest::Url,
+        media_endpoint: reqwest::Url,
+    },
+}
+
+impl PublisherConfig {
+    pub fn id(&self) -> &str {
+        match self {
+            Self::Tumblr { id, .. } => id,
+            Self::Micropub { id, .. } => id,
+        }
+    }
+
+    /// `micropub_api` holds each micropub publisher's access token, keyed by
+    /// [`Self::id`] - see [`MicropubApiConfig`] for why that token doesn't
+    /// live on this type.
+    pub fn build(
+        &self,
+        client: reqwest::Client,
+        token_store: Arc<TokenStore>,
+        micropub_api: &HashMap<String, MicropubApiConfig>,
+    ) -> Result<Box<dyn Publisher>> {
+        Ok(match self {
+            Self::Tumblr { id, blog } => {
+                Box::new(TumblrPublisher::new(id.clone(), blog.clone(), token_store))
+            }
+            Self::Micropub {
+                id,
+                endpoint,
+                media_endpoint,
+            } => {
+                let api = micropub_api.get(id).ok_or_else(|| {
+                    miette!(
+                        "no api config (access token) found for micropub publisher {:?}",
+                        id
+                    )
+                })?;
+                Box::new(MicropubPublisher::new(
+                    id.clone(),
+                    endpoint.clone(),
+                    media_endpoint.clone(),
+                    api.access_token.clone(),
+                    client,
+                ))
+            }
+        })
+    }
+}
@@ -0,0 +1,60 @@
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use miette::{Context, IntoDiagnostic, Result};
+
+use crate::{news::NewsStory, tumblr::TokenStore};
+
+use super::{alt_text_for, MediaSource, Publisher};
+
+pub struct TumblrPublisher {
+    id: String,
+    blog: String,
+    token_store: Arc<TokenStore>,
+}
+
+impl TumblrPublisher {
+    pub fn new(id: String, blog: String, token_store: Arc<TokenStore>) -> Self {
+        Self {
+            id,
+            blog,
+            token_store,
+        }
+    }
+}
+
+#[async_trait]
+impl Publisher for TumblrPublisher {
+    fn id(&self) -> &str {
+        &self.id
+    }
+
+    async fn publish(&self, story: &NewsStory, image: MediaSource) -> Result<()> {
+        let image_body = image.into_body().await?;
+        // checked/refreshed before every post, not just once at startup
+        let client = self.token_store.client().await?;
+        client
+            .create_post(
+                &self.blog,
+                vec![
+                    tumblr_api::npf::ContentBlockImage::builder(vec![
+                        tumblr_api::npf::MediaObject::builder(
+                            tumblr_api::npf::MediaObjectContent::Identifier(
+                                "image-attachment".into(),
+                            ),
+                        )
+                        .build(),
+                    ])
+                    .alt_text(alt_text_for(&story.headline))
+                    .build(),
+                ],
+            )
+            .source_url(story.story_url.clone())
+            .add_attachment(image_body, "image/png", "image-attachment")
+            .send()
+            .await
+            .into_diagnostic()
+            .wrap_err("failed to post to tumblr")?;
+        Ok(())
+    }
+}
@@ -0,0 +1,180 @@
+use std::{collections::BTreeMap, path::PathBuf};
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use miette::{Context, IntoDiagnostic, Result};
+use tokio::sync::Mutex;
+
+use crate::{news::NewsStory, publisher::MediaSource};
+
+use super::{Job, PostQueue, QueuedJob};
+
+/// JSON-file-backed [`PostQueue`], sharing the same directory-of-files shape
+/// as [`crate::store::FileSeenStore`]: one index file plus one image file per
+/// queued job.
+pub struct FileQueue {
+    dir: PathBuf,
+    index_path: PathBuf,
+    state: Mutex<FileQueueState>,
+}
+
+#[derive(Debug, Default, serde::Serialize, serde::Deserialize)]
+struct FileQueueState {
+    next_id: i64,
+    jobs: BTreeMap<i64, Job>,
+}
+
+impl FileQueue {
+    pub async fn open(dir: PathBuf) -> Result<Self> {
+        tokio::fs::create_dir_all(&dir)
+            .await
+            .into_diagnostic()
+            .wrap_err_with(|| format!("failed to create post queue dir ({})", dir.display()))?;
+        let index_path = dir.join("queue.json");
+        let state = match tokio::fs::read(&index_path).await {
+            Ok(bytes) => serde_json::from_slice(&bytes)
+                .into_diagnostic()
+                .wrap_err("failed to parse post queue")?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => FileQueueState::default(),
+            Err(e) => return Err(e).into_diagnostic().wrap_err("failed to open post queue"),
+        };
+        Ok(Self {
+            dir,
+            index_path,
+            state: Mutex::new(state),
+        })
+    }
+
+    async fn persist(&self, state: &FileQueueState) -> Result<()> {
+        let tmp_path = self.index_path.with_extension("json.tmp");
+        let data = serde_json::to_vec_pretty(state).into_diagnostic()?;
+        tokio::fs::write(&tmp_path, &data)
+            .await
+            .into_diagnostic()
+            .wrap_err("failed to write post queue")?;
+        tokio::fs::rename(&tmp_path, &self.index_path)
+            .await
+            .into_diagnostic()
+            .wrap_err("failed to persist post queue")?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl PostQueue for FileQueue {
+    async fn enqueue(&self, story: NewsStory, image: MediaSource, publisher_id: String) -> Result<()> {
+        let mut state = self.state.lock().await;
+        let id = state.next_id;
+        state.next_id += 1;
+        let image_path = self.dir.join(format!("{}.png", id));
+        image
+            .write_to_file(&image_path)
+            .await
+            .wrap_err("failed to write queued job image")?;
+        state.jobs.insert(
+            id,
+            Job {
+                story,
+                image_path,
+                publisher_id,
+                attempts: 0,
+                next_retry_at: Utc::now(),
+            },
+        );
+        self.persist(&state).await
+    }
+
+    async fn claim_next(&self) -> Result<Option<QueuedJob>> {
+        let state = self.state.lock().await;
+        let now = Utc::now();
+        Ok(state
+            .jobs
+            .iter()
+            .find(|(_, job)| job.next_retry_at <= now)
+            .map(|(&id, job)| QueuedJob {
+                id,
+                job: job.clone(),
+            }))
+    }
+
+    async fn complete(&self, job: &QueuedJob) -> Result<()> {
+        let mut state = self.state.lock().await;
+        if let Some(removed) = state.jobs.remove(&job.id) {
+            let _ = tokio::fs::remove_file(&removed.image_path).await;
+        }
+        self.persist(&state).await
+    }
+
+    async fn reschedule(&self, job: QueuedJob, next_attempt_at: DateTime<Utc>) -> Result<()> {
+        let mut state = self.state.lock().await;
+        if let Some(stored) = state.jobs.get_mut(&job.id) {
+            stored.attempts += 1;
+            stored.next_retry_at = next_attempt_at;
+        }
+        self.persist(&state).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bytes::Bytes;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn claim_then_complete_removes_the_job() {
+        let dir = std::env::temp_dir().join(format!("destielbot-test-{}", uuid::Uuid::new_v4()));
+        let queue = FileQueue::open(dir.clone()).await.unwrap();
+        let story = NewsStory {
+            id: "story-1".to_string(),
+            headline: "hello".to_string(),
+            story_url: "https://example.com/story-1".to_string(),
+        };
+
+        queue
+            .enqueue(
+                story.clone(),
+                MediaSource::InMemory(Bytes::from_static(b"fake png")),
+                "publisher-1".to_string(),
+            )
+            .await
+            .unwrap();
+
+        let claimed = queue.claim_next().await.unwrap().expect("job is due immediately");
+        assert_eq!(claimed.job.story.id, "story-1");
+        assert_eq!(claimed.job.publisher_id, "publisher-1");
+
+        queue.complete(&claimed).await.unwrap();
+        assert!(queue.claim_next().await.unwrap().is_none());
+
+        let _ = tokio::fs::remove_dir_all(&dir).await;
+    }
+
+    #[tokio::test]
+    async fn reschedule_hides_the_job_until_its_retry_time() {
+        let dir = std::env::temp_dir().join(format!("destielbot-test-{}", uuid::Uuid::new_v4()));
+        let queue = FileQueue::open(dir.clone()).await.unwrap();
+        let story = NewsStory {
+            id: "story-1".to_string(),
+            headline: "hello".to_string(),
+            story_url: "https://example.com/story-1".to_string(),
+        };
+        queue
+            .enqueue(
+                story,
+                MediaSource::InMemory(Bytes::from_static(b"fake png")),
+                "publisher-1".to_string(),
+            )
+            .await
+            .unwrap();
+        let claimed = queue.claim_next().await.unwrap().unwrap();
+
+        queue
+            .reschedule(claimed, Utc::now() + chrono::Duration::hours(1))
+            .await
+            .unwrap();
+        assert!(queue.claim_next().await.unwrap().is_none());
+
+        let _ = tokio::fs::remove_dir_all(&dir).await;
+    }
+}
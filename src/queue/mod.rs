@@ -0,0 +1,197 @@
+//! Durable retry queue for posts: generating the image and posting it are two
+//! separate steps, so a post that fails (rate limit, network blip, tumblr being
+//! tumblr) gets retried with backoff instead of the story just being lost - it
+//! was already marked seen the moment it was polled.
+
+mod file;
+mod sqlite;
+
+use std::{future::Future, path::PathBuf, time::Duration};
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use miette::Result;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+pub use file::FileQueue;
+pub use sqlite::SqliteQueue;
+
+use crate::{news::NewsStory, publisher::MediaSource, store::SeenStore};
+
+/// a single queued post: the story, the already-rendered image for it, and
+/// how many times (and when next) to retry.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Job {
+    pub story: NewsStory,
+    pub image_path: PathBuf,
+    /// which configured [`crate::publisher::Publisher`] this job should be
+    /// handed to - lets each publisher's posts succeed/retry independently
+    pub publisher_id: String,
+    pub attempts: u32,
+    pub next_retry_at: DateTime<Utc>,
+}
+
+/// a [`Job`] together with the backend-assigned id needed to [`PostQueue::complete`]
+/// or [`PostQueue::reschedule`] it.
+#[derive(Debug, Clone)]
+pub struct QueuedJob {
+    pub id: i64,
+    pub job: Job,
+}
+
+#[async_trait]
+pub trait PostQueue: Send + Sync {
+    async fn enqueue(&self, story: NewsStory, image: MediaSource, publisher_id: String) -> Result<()>;
+    /// pop the next job that's due for an attempt, if any
+    async fn claim_next(&self) -> Result<Option<QueuedJob>>;
+    /// remove a job permanently - either it posted, or we're giving up on it
+    async fn complete(&self, job: &QueuedJob) -> Result<()>;
+    /// bump the attempt count and push the retry time back
+    async fn reschedule(&self, job: QueuedJob, next_attempt_at: DateTime<Utc>) -> Result<()>;
+}
+
+/// selects which [`PostQueue`] backend to use - backed by the same storage
+/// options as [`crate::store::SeenStoreConfig`].
+#[derive(Debug, Deserialize, JsonSchema)]
+#[serde(tag = "backend", rename_all = "snake_case")]
+pub enum PostQueueConfig {
+    File { dir: PathBuf },
+    Sqlite { path: PathBuf, image_dir: PathBuf },
+}
+
+impl Default for PostQueueConfig {
+    fn default() -> Self {
+        Self::File {
+            dir: PathBuf::from("post_queue"),
+        }
+    }
+}
+
+impl PostQueueConfig {
+    pub async fn build(&self) -> Result<Box<dyn PostQueue>> {
+        Ok(match self {
+            Self::File { dir } => Box::new(FileQueue::open(dir.clone()).await?),
+            Self::Sqlite { path, image_dir } => {
+                Box::new(SqliteQueue::open(path, image_dir.clone()).await?)
+            }
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct BackoffConfig {
+    pub base: Duration,
+    pub max: Duration,
+    pub max_attempts: u32,
+}
+
+impl Default for BackoffConfig {
+    fn default() -> Self {
+        Self {
+            base: Duration::from_secs(30),
+            max: Duration::from_secs(60 * 60),
+            max_attempts: 8,
+        }
+    }
+}
+
+/// `base * 2^attempts`, capped at `max`.
+pub fn backoff_delay(attempts: u32, base: Duration, max: Duration) -> Duration {
+    match base.checked_mul(1u32.checked_shl(attempts).unwrap_or(u32::MAX)) {
+        Some(delay) => delay.min(max),
+        None => max,
+    }
+}
+
+/// drains `queue` concurrently with the polling loop: claims due jobs one at a
+/// time, hands each to `publish`, and reschedules with exponential backoff on
+/// failure (dropping the job once `backoff.max_attempts` is exceeded). Only
+/// marks the story posted in `seen_store` once `publish` actually succeeds.
+///
+/// a transient error talking to `queue`/`seen_store` itself (as opposed to a
+/// failure from `publish`) is logged and retried rather than returned - this
+/// runs as a detached `tokio::spawn` task in `main.rs`, so returning `Err`
+/// here would silently kill retries for the rest of the process's life.
+pub async fn run_worker<F, Fut>(
+    queue: &dyn PostQueue,
+    seen_store: &dyn SeenStore,
+    backoff: BackoffConfig,
+    mut publish: F,
+) -> Result<()>
+where
+    F: FnMut(Job) -> Fut,
+    Fut: Future<Output = Result<()>>,
+{
+    loop {
+        let queued = match queue.claim_next().await {
+            Ok(queued) => queued,
+            Err(err) => {
+                tracing::error!("failed to claim next post queue job, retrying: {:?}", err);
+                tokio::time::sleep(Duration::from_secs(1)).await;
+                continue;
+            }
+        };
+        let Some(queued) = queued else {
+            tokio::time::sleep(Duration::from_secs(1)).await;
+            continue;
+        };
+        let story_id = queued.job.story.id.clone();
+        let attempts = queued.job.attempts;
+        match publish(queued.job.clone()).await {
+            Ok(()) => {
+                if let Err(err) = queue.complete(&queued).await {
+                    tracing::error!("failed to complete post job for {}: {:?}", story_id, err);
+                }
+                if let Err(err) = seen_store.mark_posted(&story_id).await {
+                    tracing::error!("failed to mark {} posted: {:?}", story_id, err);
+                }
+            }
+            Err(err) if attempts + 1 >= backoff.max_attempts => {
+                tracing::error!(
+                    "dropping post job for {} after {} attempts: {:?}",
+                    story_id,
+                    attempts + 1,
+                    err
+                );
+                if let Err(err) = queue.complete(&queued).await {
+                    tracing::error!(
+                        "failed to remove dropped post job for {}: {:?}",
+                        story_id,
+                        err
+                    );
+                }
+            }
+            Err(err) => {
+                let delay = backoff_delay(attempts, backoff.base, backoff.max);
+                tracing::warn!(
+                    "post job for {} failed (attempt {}), retrying in {:?}: {:?}",
+                    story_id,
+                    attempts + 1,
+                    delay,
+                    err
+                );
+                let next_attempt_at = Utc::now()
+                    + chrono::Duration::from_std(delay).unwrap_or(chrono::Duration::seconds(60));
+                if let Err(err) = queue.reschedule(queued, next_attempt_at).await {
+                    tracing::error!("failed to reschedule post job for {}: {:?}", story_id, err);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn backoff_doubles_and_caps() {
+        let base = Duration::from_secs(1);
+        let max = Duration::from_secs(10);
+        assert_eq!(backoff_delay(0, base, max), Duration::from_secs(1));
+        assert_eq!(backoff_delay(1, base, max), Duration::from_secs(2));
+        assert_eq!(backoff_delay(2, base, max), Duration::from_secs(4));
+        assert_eq!(backoff_delay(10, base, max), max);
+    }
+}
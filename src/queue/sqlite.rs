@@ -0,0 +1,186 @@
+use std::path::PathBuf;
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use miette::{Context, IntoDiagnostic, Result};
+use sqlx::sqlite::SqlitePoolOptions;
+use sqlx::{Row, SqlitePool};
+
+use crate::{news::NewsStory, publisher::MediaSource};
+
+use super::{Job, PostQueue, QueuedJob};
+
+pub struct SqliteQueue {
+    pool: SqlitePool,
+    image_dir: PathBuf,
+}
+
+impl SqliteQueue {
+    pub async fn open(path: &std::path::Path, image_dir: PathBuf) -> Result<Self> {
+        tokio::fs::create_dir_all(&image_dir)
+            .await
+            .into_diagnostic()
+            .wrap_err("failed to create post queue image dir")?;
+        let pool = SqlitePoolOptions::new()
+            .connect(&format!("sqlite:{}?mode=rwc", path.display()))
+            .await
+            .into_diagnostic()?;
+        sqlx::query(
+            "CREATE TABLE IF NOT EXISTS post_queue (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                story_json TEXT NOT NULL,
+                image_path TEXT NOT NULL,
+                publisher_id TEXT NOT NULL,
+                attempts INTEGER NOT NULL,
+                next_retry_at TEXT NOT NULL
+            )",
+        )
+        .execute(&pool)
+        .await
+        .into_diagnostic()?;
+        Ok(Self { pool, image_dir })
+    }
+}
+
+#[async_trait]
+impl PostQueue for SqliteQueue {
+    async fn enqueue(&self, story: NewsStory, image: MediaSource, publisher_id: String) -> Result<()> {
+        let image_path = self.image_dir.join(format!("{}.png", uuid::Uuid::new_v4()));
+        image
+            .write_to_file(&image_path)
+            .await
+            .wrap_err("failed to write queued job image")?;
+        let story_json = serde_json::to_string(&story).into_diagnostic()?;
+        sqlx::query(
+            "INSERT INTO post_queue (story_json, image_path, publisher_id, attempts, next_retry_at) VALUES (?1, ?2, ?3, 0, ?4)",
+        )
+        .bind(story_json)
+        .bind(image_path.to_string_lossy().to_string())
+        .bind(publisher_id)
+        .bind(Utc::now().to_rfc3339())
+        .execute(&self.pool)
+        .await
+        .into_diagnostic()?;
+        Ok(())
+    }
+
+    async fn claim_next(&self) -> Result<Option<QueuedJob>> {
+        let row = sqlx::query(
+            "SELECT id, story_json, image_path, publisher_id, attempts, next_retry_at FROM post_queue
+             WHERE next_retry_at <= ?1 ORDER BY id LIMIT 1",
+        )
+        .bind(Utc::now().to_rfc3339())
+        .fetch_optional(&self.pool)
+        .await
+        .into_diagnostic()?;
+        let Some(row) = row else {
+            return Ok(None);
+        };
+        let story: NewsStory =
+            serde_json::from_str(&row.get::<String, _>("story_json")).into_diagnostic()?;
+        let next_retry_at = DateTime::parse_from_rfc3339(&row.get::<String, _>("next_retry_at"))
+            .into_diagnostic()?
+            .with_timezone(&Utc);
+        Ok(Some(QueuedJob {
+            id: row.get("id"),
+            job: Job {
+                story,
+                image_path: PathBuf::from(row.get::<String, _>("image_path")),
+                publisher_id: row.get("publisher_id"),
+                attempts: row.get::<i64, _>("attempts") as u32,
+                next_retry_at,
+            },
+        }))
+    }
+
+    async fn complete(&self, job: &QueuedJob) -> Result<()> {
+        let _ = tokio::fs::remove_file(&job.job.image_path).await;
+        sqlx::query("DELETE FROM post_queue WHERE id = ?1")
+            .bind(job.id)
+            .execute(&self.pool)
+            .await
+            .into_diagnostic()?;
+        Ok(())
+    }
+
+    async fn reschedule(&self, job: QueuedJob, next_attempt_at: DateTime<Utc>) -> Result<()> {
+        sqlx::query("UPDATE post_queue SET attempts = attempts + 1, next_retry_at = ?1 WHERE id = ?2")
+            .bind(next_attempt_at.to_rfc3339())
+            .bind(job.id)
+            .execute(&self.pool)
+            .await
+            .into_diagnostic()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bytes::Bytes;
+
+    use super::*;
+
+    async fn open_test_queue() -> (SqliteQueue, PathBuf, PathBuf) {
+        let db_path = std::env::temp_dir().join(format!("destielbot-test-{}.sqlite3", uuid::Uuid::new_v4()));
+        let image_dir = std::env::temp_dir().join(format!("destielbot-test-images-{}", uuid::Uuid::new_v4()));
+        let queue = SqliteQueue::open(&db_path, image_dir.clone()).await.unwrap();
+        (queue, db_path, image_dir)
+    }
+
+    #[tokio::test]
+    async fn claim_then_complete_removes_the_job() {
+        let (queue, db_path, image_dir) = open_test_queue().await;
+        let story = NewsStory {
+            id: "story-1".to_string(),
+            headline: "hello".to_string(),
+            story_url: "https://example.com/story-1".to_string(),
+        };
+
+        queue
+            .enqueue(
+                story.clone(),
+                MediaSource::InMemory(Bytes::from_static(b"fake png")),
+                "publisher-1".to_string(),
+            )
+            .await
+            .unwrap();
+
+        let claimed = queue.claim_next().await.unwrap().expect("job is due immediately");
+        assert_eq!(claimed.job.story.id, "story-1");
+        assert_eq!(claimed.job.publisher_id, "publisher-1");
+
+        queue.complete(&claimed).await.unwrap();
+        assert!(queue.claim_next().await.unwrap().is_none());
+
+        let _ = std::fs::remove_file(&db_path);
+        let _ = std::fs::remove_dir_all(&image_dir);
+    }
+
+    #[tokio::test]
+    async fn reschedule_hides_the_job_until_its_retry_time() {
+        let (queue, db_path, image_dir) = open_test_queue().await;
+        let story = NewsStory {
+            id: "story-1".to_string(),
+            headline: "hello".to_string(),
+            story_url: "https://example.com/story-1".to_string(),
+        };
+        queue
+            .enqueue(
+                story,
+                MediaSource::InMemory(Bytes::from_static(b"fake png")),
+                "publisher-1".to_string(),
+            )
+            .await
+            .unwrap();
+        let claimed = queue.claim_next().await.unwrap().unwrap();
+
+        queue
+            .reschedule(claimed, Utc::now() + chrono::Duration::hours(1))
+            .await
+            .unwrap();
+        assert!(queue.claim_next().await.unwrap().is_none());
+
+        let _ = std::fs::remove_file(&db_path);
+        let _ = std::fs::remove_dir_all(&image_dir);
+    }
+}
@@ -0,0 +1,98 @@
+use std::{collections::HashSet, path::PathBuf};
+
+use async_trait::async_trait;
+use miette::{Context, IntoDiagnostic, Result};
+use tokio::sync::Mutex;
+
+use super::SeenStore;
+
+/// JSON-backed [`SeenStore`]. Writes are full-file rewrites (this isn't meant to
+/// scale to huge histories), but they're written to a temp file and renamed into
+/// place so a crash mid-write can't corrupt the store.
+pub struct FileSeenStore {
+    path: PathBuf,
+    state: Mutex<FileState>,
+}
+
+#[derive(Debug, Default, serde::Serialize, serde::Deserialize)]
+struct FileState {
+    seen: HashSet<String>,
+    posted: HashSet<String>,
+}
+
+impl FileSeenStore {
+    pub async fn open(path: PathBuf) -> Result<Self> {
+        let state = match tokio::fs::read(&path).await {
+            Ok(bytes) => serde_json::from_slice(&bytes)
+                .into_diagnostic()
+                .wrap_err_with(|| format!("failed to parse seen store ({})", path.display()))?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => FileState::default(),
+            Err(e) => {
+                return Err(e)
+                    .into_diagnostic()
+                    .wrap_err_with(|| format!("failed to open seen store ({})", path.display()))
+            }
+        };
+        Ok(Self {
+            path,
+            state: Mutex::new(state),
+        })
+    }
+
+    async fn persist(&self, state: &FileState) -> Result<()> {
+        let tmp_path = self.path.with_extension("json.tmp");
+        let data = serde_json::to_vec_pretty(state).into_diagnostic()?;
+        tokio::fs::write(&tmp_path, &data)
+            .await
+            .into_diagnostic()
+            .wrap_err("failed to write seen store")?;
+        tokio::fs::rename(&tmp_path, &self.path)
+            .await
+            .into_diagnostic()
+            .wrap_err("failed to persist seen store")?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl SeenStore for FileSeenStore {
+    async fn contains(&self, id: &str) -> Result<bool> {
+        Ok(self.state.lock().await.seen.contains(id))
+    }
+
+    async fn insert(&self, id: &str) -> Result<()> {
+        let mut state = self.state.lock().await;
+        state.seen.insert(id.to_string());
+        self.persist(&state).await
+    }
+
+    async fn mark_posted(&self, id: &str) -> Result<()> {
+        let mut state = self.state.lock().await;
+        state.posted.insert(id.to_string());
+        self.persist(&state).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn insert_is_visible_to_contains_and_survives_a_reopen() {
+        let path = std::env::temp_dir().join(format!("destielbot-test-{}.json", uuid::Uuid::new_v4()));
+
+        let store = FileSeenStore::open(path.clone()).await.unwrap();
+        assert!(!store.contains("story-1").await.unwrap());
+        store.insert("story-1").await.unwrap();
+        assert!(store.contains("story-1").await.unwrap());
+        store.mark_posted("story-1").await.unwrap();
+
+        // re-opening from disk should see the same state
+        let reopened = FileSeenStore::open(path.clone()).await.unwrap();
+        assert!(reopened.contains("story-1").await.unwrap());
+        assert!(!reopened.contains("story-2").await.unwrap());
+
+        let _ = std::fs::remove_file(&path);
+        let _ = std::fs::remove_file(path.with_extension("json.tmp"));
+    }
+}
@@ -0,0 +1,66 @@
+//! Persistent tracking of which [`NewsStory`][crate::news::NewsStory]s we've already
+//! handled, so a restarted bot doesn't re-post stories it already posted (or re-queue
+//! ones it's already in the middle of posting).
+
+mod file;
+#[cfg(feature = "redis")]
+mod redis;
+mod sqlite;
+
+use std::path::PathBuf;
+
+use async_trait::async_trait;
+use miette::Result;
+use schemars::JsonSchema;
+use serde::Deserialize;
+
+pub use file::FileSeenStore;
+#[cfg(feature = "redis")]
+pub use redis::RedisSeenStore;
+pub use sqlite::SqliteSeenStore;
+
+/// Dedup store keyed on [`NewsStory::id`][crate::news::NewsStory::id], not the story
+/// URL, since a source's URL scheme/path can change without the story itself changing.
+#[async_trait]
+pub trait SeenStore: Send + Sync {
+    /// whether `id` has already been seen (regardless of whether it was ever
+    /// successfully posted)
+    async fn contains(&self, id: &str) -> Result<bool>;
+    /// record that `id` has been seen, so future polls skip it
+    async fn insert(&self, id: &str) -> Result<()>;
+    /// record that `id` was successfully posted. only call this once the post
+    /// (or, with the retry queue, the queued job) actually completes - a crash
+    /// between image generation and posting should not look like a success.
+    async fn mark_posted(&self, id: &str) -> Result<()>;
+}
+
+/// selects which [`SeenStore`] backend to use, mirroring how e.g. the IndieWeb
+/// server splits its `database` config across `file`/`postgres`/`redis`.
+#[derive(Debug, Deserialize, JsonSchema)]
+#[serde(tag = "backend", rename_all = "snake_case")]
+pub enum SeenStoreConfig {
+    /// default backend - a JSON file, good enough for a single-instance bot
+    File { path: PathBuf },
+    Sqlite { path: PathBuf },
+    #[cfg(feature = "redis")]
+    Redis { url: String },
+}
+
+impl Default for SeenStoreConfig {
+    fn default() -> Self {
+        Self::File {
+            path: PathBuf::from("seen_stories.json"),
+        }
+    }
+}
+
+impl SeenStoreConfig {
+    pub async fn build(&self) -> Result<Box<dyn SeenStore>> {
+        Ok(match self {
+            Self::File { path } => Box::new(FileSeenStore::open(path.clone()).await?),
+            Self::Sqlite { path } => Box::new(SqliteSeenStore::open(path).await?),
+            #[cfg(feature = "redis")]
+            Self::Redis { url } => Box::new(RedisSeenStore::open(url).await?),
+        })
+    }
+}
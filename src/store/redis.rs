@@ -0,0 +1,54 @@
+use async_trait::async_trait;
+use miette::{IntoDiagnostic, Result};
+use redis::AsyncCommands;
+
+use super::SeenStore;
+
+/// optional backend for setups that already run redis for other things and
+/// would rather not add a sqlite file.
+pub struct RedisSeenStore {
+    client: redis::Client,
+    seen_key: String,
+    posted_key: String,
+}
+
+impl RedisSeenStore {
+    pub async fn open(url: &str) -> Result<Self> {
+        let client = redis::Client::open(url).into_diagnostic()?;
+        // fail fast if the url/connection is bad rather than on the first poll
+        let _: () = client
+            .get_multiplexed_async_connection()
+            .await
+            .into_diagnostic()?
+            .ping()
+            .await
+            .into_diagnostic()?;
+        Ok(Self {
+            client,
+            seen_key: "destielbot:seen".to_string(),
+            posted_key: "destielbot:posted".to_string(),
+        })
+    }
+
+    async fn conn(&self) -> Result<redis::aio::MultiplexedConnection> {
+        self.client
+            .get_multiplexed_async_connection()
+            .await
+            .into_diagnostic()
+    }
+}
+
+#[async_trait]
+impl SeenStore for RedisSeenStore {
+    async fn contains(&self, id: &str) -> Result<bool> {
+        self.conn().await?.sismember(&self.seen_key, id).await.into_diagnostic()
+    }
+
+    async fn insert(&self, id: &str) -> Result<()> {
+        self.conn().await?.sadd(&self.seen_key, id).await.into_diagnostic()
+    }
+
+    async fn mark_posted(&self, id: &str) -> Result<()> {
+        self.conn().await?.sadd(&self.posted_key, id).await.into_diagnostic()
+    }
+}
@@ -0,0 +1,85 @@
+use std::path::Path;
+
+use async_trait::async_trait;
+use miette::{IntoDiagnostic, Result};
+use sqlx::sqlite::SqlitePoolOptions;
+use sqlx::SqlitePool;
+
+use super::SeenStore;
+
+pub struct SqliteSeenStore {
+    pool: SqlitePool,
+}
+
+impl SqliteSeenStore {
+    pub async fn open(path: &Path) -> Result<Self> {
+        let pool = SqlitePoolOptions::new()
+            .connect(&format!("sqlite:{}?mode=rwc", path.display()))
+            .await
+            .into_diagnostic()?;
+        sqlx::query(
+            "CREATE TABLE IF NOT EXISTS seen_stories (
+                id TEXT PRIMARY KEY,
+                posted_at TEXT
+            )",
+        )
+        .execute(&pool)
+        .await
+        .into_diagnostic()?;
+        Ok(Self { pool })
+    }
+}
+
+#[async_trait]
+impl SeenStore for SqliteSeenStore {
+    async fn contains(&self, id: &str) -> Result<bool> {
+        let row: Option<(String,)> = sqlx::query_as("SELECT id FROM seen_stories WHERE id = ?")
+            .bind(id)
+            .fetch_optional(&self.pool)
+            .await
+            .into_diagnostic()?;
+        Ok(row.is_some())
+    }
+
+    async fn insert(&self, id: &str) -> Result<()> {
+        sqlx::query("INSERT OR IGNORE INTO seen_stories (id, posted_at) VALUES (?, NULL)")
+            .bind(id)
+            .execute(&self.pool)
+            .await
+            .into_diagnostic()?;
+        Ok(())
+    }
+
+    async fn mark_posted(&self, id: &str) -> Result<()> {
+        sqlx::query("UPDATE seen_stories SET posted_at = ?1 WHERE id = ?2")
+            .bind(chrono::Utc::now().to_rfc3339())
+            .bind(id)
+            .execute(&self.pool)
+            .await
+            .into_diagnostic()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn insert_is_visible_to_contains_and_mark_posted_is_idempotent() {
+        let path = std::env::temp_dir().join(format!("destielbot-test-{}.sqlite3", uuid::Uuid::new_v4()));
+
+        let store = SqliteSeenStore::open(&path).await.unwrap();
+        assert!(!store.contains("story-1").await.unwrap());
+        store.insert("story-1").await.unwrap();
+        assert!(store.contains("story-1").await.unwrap());
+
+        // inserting twice, and marking posted on an id that isn't seen, should
+        // both be no-ops rather than errors
+        store.insert("story-1").await.unwrap();
+        store.mark_posted("story-2").await.unwrap();
+        assert!(!store.contains("story-2").await.unwrap());
+
+        let _ = std::fs::remove_file(&path);
+    }
+}
@@ -1,3 +1,5 @@
+use std::path::PathBuf;
+
 use miette::{Context, IntoDiagnostic, Result};
 use oauth2::{
     basic::BasicClient, reqwest::async_http_client, AuthUrl, ClientId, ClientSecret, Scope,
@@ -5,6 +7,7 @@ use oauth2::{
 };
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
+use tokio::sync::Mutex;
 use tumblr_api::client::Credentials;
 
 #[derive(Debug, Deserialize, JsonSchema)]
@@ -33,51 +36,133 @@ impl TokenInfo {
     }
 }
 
-pub async fn tumblr_auth_test(api_config: &TumblrApiConfig) -> Result<()> {
-    let client = BasicClient::new(
-        ClientId::new(api_config.client_id.clone()),
-        Some(ClientSecret::new(api_config.client_secret.clone())),
-        AuthUrl::new("https://www.tumblr.com/oauth2/authorize".to_string()).into_diagnostic()?,
-        Some(
-            TokenUrl::new("https://api.tumblr.com/v2/oauth2/token".to_string())
+/// keeps the cached `.oauth2-token.json` fresh across a long-running process (and
+/// across restarts): re-uses it while it's still valid, refreshes it with the
+/// refresh token when tumblr gave us one, and only falls back to a full
+/// client-credentials exchange when neither of those is possible. This keeps us
+/// from hammering tumblr's token endpoint every time the bot restarts.
+pub struct TokenStore {
+    token_path: PathBuf,
+    oauth_client: BasicClient,
+    cached: Mutex<Option<TokenInfo>>,
+}
+
+impl TokenStore {
+    pub fn new(api_config: &TumblrApiConfig, token_path: PathBuf) -> Result<Self> {
+        let oauth_client = BasicClient::new(
+            ClientId::new(api_config.client_id.clone()),
+            Some(ClientSecret::new(api_config.client_secret.clone())),
+            AuthUrl::new("https://www.tumblr.com/oauth2/authorize".to_string())
                 .into_diagnostic()?,
-        ),
-    );
+            Some(
+                TokenUrl::new("https://api.tumblr.com/v2/oauth2/token".to_string())
+                    .into_diagnostic()?,
+            ),
+        );
+        Ok(Self {
+            token_path,
+            oauth_client,
+            cached: Mutex::new(None),
+        })
+    }
 
-    let request_time = chrono::Utc::now();
+    async fn load_cached_from_disk(&self) -> Option<TokenInfo> {
+        let bytes = tokio::fs::read(&self.token_path).await.ok()?;
+        serde_json::from_slice(&bytes).ok()
+    }
 
-    let token_result = client
-        .exchange_client_credentials()
-        .add_scope(Scope::new("write".to_string()))
-        .request_async(async_http_client)
-        .await
-        .into_diagnostic()?;
+    async fn persist(&self, info: &TokenInfo) -> Result<()> {
+        let data = serde_json::to_vec_pretty(info).into_diagnostic()?;
+        tokio::fs::write(&self.token_path, &data)
+            .await
+            .into_diagnostic()
+            .wrap_err_with(|| {
+                format!("failed to persist oauth2 token ({})", self.token_path.display())
+            })
+    }
 
-    let token_file = std::fs::OpenOptions::new()
-        .write(true)
-        .create(true)
-        .truncate(true)
-        .open(".oauth2-token.json")
-        .into_diagnostic()
-        .wrap_err("failed to open token file for writing")?;
-
-    serde_json::to_writer_pretty(
-        token_file,
-        &TokenInfo {
-            request_time,
+    /// returns a currently-valid access token, refreshing or re-authenticating
+    /// first if the cached one is missing or expired. call this before each
+    /// posting batch rather than caching a `Client` for the whole run.
+    pub async fn access_token(&self) -> Result<String> {
+        let mut cached = self.cached.lock().await;
+        if cached.is_none() {
+            *cached = self.load_cached_from_disk().await;
+        }
+        if let Some(info) = cached.as_ref() {
+            if !info.is_expired()? {
+                return Ok(info.token_result.access_token().secret().clone());
+            }
+        }
+
+        let refresh_result = match cached.as_ref().and_then(|info| info.token_result.refresh_token())
+        {
+            Some(refresh_token) => {
+                tracing::debug!("tumblr oauth2 token expired, refreshing it");
+                match self
+                    .oauth_client
+                    .exchange_refresh_token(refresh_token)
+                    .request_async(async_http_client)
+                    .await
+                {
+                    Ok(token_result) => Some(token_result),
+                    Err(err) => {
+                        // the refresh token itself can be revoked/expired -
+                        // fall back to a full re-auth instead of wedging
+                        // every future post behind this same failing refresh
+                        tracing::warn!(
+                            "failed to refresh tumblr oauth2 token, falling back to a full re-auth: {:?}",
+                            err
+                        );
+                        None
+                    }
+                }
+            }
+            None => None,
+        };
+
+        let token_result = match refresh_result {
+            Some(token_result) => token_result,
+            None => {
+                tracing::debug!(
+                    "no usable tumblr oauth2 token cached, doing a full client-credentials exchange"
+                );
+                self.oauth_client
+                    .exchange_client_credentials()
+                    .add_scope(Scope::new("write".to_string()))
+                    .request_async(async_http_client)
+                    .await
+                    .into_diagnostic()
+                    .wrap_err("failed to authenticate with tumblr")?
+            }
+        };
+
+        let info = TokenInfo {
+            request_time: chrono::Utc::now(),
             token_result,
-        },
-    )
-    .into_diagnostic()?;
+        };
+        self.persist(&info).await?;
+        let access_token = info.token_result.access_token().secret().clone();
+        *cached = Some(info);
+        Ok(access_token)
+    }
 
+    /// builds a [`tumblr_api::client::Client`] bearing a currently-valid token
+    pub async fn client(&self) -> Result<tumblr_api::client::Client> {
+        let access_token = self.access_token().await?;
+        Ok(tumblr_api::client::Client::new(Credentials::new_oauth2_token(access_token)))
+    }
+}
+
+pub async fn tumblr_auth_test(api_config: &TumblrApiConfig) -> Result<()> {
+    let token_store = TokenStore::new(api_config, PathBuf::from(".oauth2-token.json"))?;
+    token_store.access_token().await?;
     Ok(())
 }
 
 pub async fn tumblr_api_test(api_config: &TumblrApiConfig) -> Result<()> {
-    let client = tumblr_api::client::Client::new(Credentials::new_oauth2(
-        api_config.client_id.clone(),
-        api_config.client_secret.clone(),
-    ));
+    let token_store = TokenStore::new(api_config, PathBuf::from(".oauth2-token.json"))?;
+    let client = token_store.client().await?;
 
     let image_bytes = std::fs::read("./generated_0.png").into_diagnostic()?;
 
@@ -110,3 +195,47 @@ pub async fn tumblr_api_test(api_config: &TumblrApiConfig) -> Result<()> {
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use oauth2::{AccessToken, EmptyExtraTokenFields, StandardTokenResponse};
+
+    use super::*;
+
+    fn token_info(expires_in: Option<std::time::Duration>, request_time: chrono::DateTime<chrono::Utc>) -> TokenInfo {
+        let mut token_result = StandardTokenResponse::new(
+            AccessToken::new("tok".to_string()),
+            oauth2::basic::BasicTokenType::Bearer,
+            EmptyExtraTokenFields {},
+        );
+        token_result.set_expires_in(expires_in.as_ref());
+        TokenInfo {
+            request_time,
+            token_result,
+        }
+    }
+
+    #[test]
+    fn token_within_its_expiry_window_is_not_expired() {
+        let info = token_info(Some(std::time::Duration::from_secs(3600)), chrono::Utc::now());
+        assert!(!info.is_expired().unwrap());
+    }
+
+    #[test]
+    fn token_past_its_expiry_window_is_expired() {
+        let info = token_info(
+            Some(std::time::Duration::from_secs(60)),
+            chrono::Utc::now() - chrono::Duration::hours(1),
+        );
+        assert!(info.is_expired().unwrap());
+    }
+
+    #[test]
+    fn token_with_no_expiry_is_treated_as_expired() {
+        // tumblr always sends `expires_in`, but don't trust a cached token
+        // that's somehow missing it - treat it the same as already expired
+        // rather than reusing it forever
+        let info = token_info(None, chrono::Utc::now());
+        assert!(info.is_expired().unwrap());
+    }
+}